@@ -8,11 +8,14 @@
 use std::{
     fmt::Display,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Read},
+    path::Path,
 };
 
 use bstr::BStr;
 use memmap2::Mmap;
+#[allow(unused_imports)]
+use memmap2::MmapOptions;
 
 // FxHashMap is noticably faster than a vanilla HashMap.
 use rustc_hash::FxHashMap as HashMap;
@@ -50,7 +53,7 @@ impl Display for CityDetails {
             f,
             "{:.1}/{:.1}/{:.1}",
             self.min as f64 / 10.0,
-            self.sum as f64 / self.count as f64 / 10.0,
+            self.rounded_mean(),
             self.max as f64 / 10.0
         )
     }
@@ -67,6 +70,31 @@ impl CityDetails {
         self.sum += i32::from(meas);
         self.count += 1;
     }
+
+    /// The mean (in degrees) rounded to one decimal place, half-up toward
+    /// positive infinity.
+    ///
+    /// `{:.1}` uses Rust's round-half-to-even, but the challenge's reference
+    /// output rounds half up, so edge cases like `x.x5` can disagree. We round
+    /// explicitly in fixed point: `sum` is already ×10, so the mean in tenths is
+    /// `sum / count`; rounding that to the nearest integer via
+    /// `floor((2*sum + count) / (2*count))` (Euclidean division, which floors
+    /// for positive divisors) gives half-up for both signs.
+    pub fn rounded_mean(&self) -> f64 {
+        let sum = i64::from(self.sum);
+        let count = i64::from(self.count);
+        let tenths = (2 * sum + count).div_euclid(2 * count);
+        tenths as f64 / 10.0
+    }
+
+    /// Fold another partial result for the same city into this one. Used when
+    /// merging the per-thread maps produced by [`read_mmap_parallel`].
+    fn merge(&mut self, other: &CityDetails) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.count += other.count;
+    }
 }
 
 // Strategy:
@@ -121,6 +149,112 @@ fn parse_digits(bytes: &[u8]) -> i16 {
     mul * acc
 }
 
+// Branchlessly parse a measurement field of the form `-?\d?\d.\d` (range
+// -99.9..=99.9) into its value ×10, using the SWAR ("SIMD within a register")
+// trick popularised by the fastest community solutions.
+//
+// The field bytes are loaded little-endian into a `u64`. The 0x10 bit is set
+// for ASCII digits (`0x30..=0x39`) but clear for `.` (`0x2e`) and `-` (`0x2d`),
+// so masking against `0x10101000` and counting trailing zeros locates the
+// decimal point without a branch; that position also tells us whether there are
+// one or two integer digits. The `-` byte is masked away, the remaining digits
+// are folded with fixed multipliers into `tens*100 + ones*10 + frac`, and the
+// sign is applied via `(value ^ mask) - mask`.
+#[cfg(feature = "swar")]
+pub fn parse_temp_swar(bytes: &[u8]) -> i16 {
+    // Load up to 8 bytes little-endian; missing high bytes stay zero, which is
+    // harmless since the decimal point and digits live in the low bytes.
+    let mut buf = [0u8; 8];
+    let n = bytes.len().min(8);
+    buf[..n].copy_from_slice(&bytes[..n]);
+    let word = u64::from_le_bytes(buf);
+
+    // Position (in bits) of the decimal point.
+    let dot_pos = (!word & 0x1010_1000).trailing_zeros();
+
+    // All-ones mask when the value is negative, zero otherwise, derived from the
+    // 0x10 bit of the first byte (clear for `-`).
+    let sign_mask = ((!word << 59) as i64 >> 63) as u64;
+    // Zero out the `-` byte so it doesn't pollute the digit arithmetic.
+    let design_mask = !(sign_mask & 0xFF);
+
+    let digits = ((word & design_mask) << (28 - dot_pos)) & 0x0F00_0F0F_00;
+    let abs = (digits.wrapping_mul(0x640A_0001) >> 32) & 0x3FF;
+
+    ((abs ^ sign_mask).wrapping_sub(sign_mask)) as i16
+}
+
+// Hash a city bytestring with the same FxHash algorithm backing our HashMap, so
+// the cached hashes stored in `StationTable` stay consistent with lookups.
+fn fxhash(key: &[u8]) -> u64 {
+    use std::hash::Hasher;
+    let mut hasher = rustc_hash::FxHasher::default();
+    hasher.write(key);
+    hasher.finish()
+}
+
+/// A purpose-built open-addressing table keyed on city bytestrings.
+///
+/// Profiling the external posts repeatedly fingers the generic `HashMap`
+/// indirection as the bottleneck, so the hot path uses this instead: a
+/// power-of-two-sized slot array with linear probing, caching each key's
+/// FxHash alongside it so a probe compares the cheap `u64` before ever touching
+/// the byte slice. The station count (~10k) is tiny next to the fixed capacity,
+/// so no resize ever happens during a run.
+pub struct StationTable<'a> {
+    slots: Vec<Option<(u64, &'a BStr, CityDetails)>>,
+    mask: usize,
+}
+
+impl Default for StationTable<'_> {
+    fn default() -> Self {
+        // 128Ki slots keeps the load factor well under 10% for ~10k stations.
+        const CAPACITY: usize = 1 << 17;
+        let mut slots = Vec::with_capacity(CAPACITY);
+        slots.resize_with(CAPACITY, || None);
+        StationTable {
+            slots,
+            mask: CAPACITY - 1,
+        }
+    }
+}
+
+impl<'a> StationTable<'a> {
+    /// Look up `key` (whose FxHash is `hash`) and return a mutable reference to
+    /// its details, inserting a default entry on the first sighting.
+    pub fn entry_or_default(&mut self, hash: u64, key: &'a BStr) -> &mut CityDetails {
+        let mut idx = (hash as usize) & self.mask;
+        loop {
+            match self.slots[idx].as_ref() {
+                Some((h, k, _)) => {
+                    if *h == hash && *k == key {
+                        break;
+                    }
+                    idx = (idx + 1) & self.mask;
+                }
+                None => {
+                    self.slots[idx] = Some((hash, key, CityDetails::default()));
+                    break;
+                }
+            }
+        }
+        &mut self.slots[idx].as_mut().unwrap().2
+    }
+
+    /// Drain the occupied slots and return them sorted by city name, matching
+    /// the shape the rest of the crate (and `print`) expects.
+    pub fn into_sorted_vec(self) -> Vec<(&'a BStr, CityDetails)> {
+        let mut out: Vec<_> = self
+            .slots
+            .into_iter()
+            .flatten()
+            .map(|(_, city, details)| (city, details))
+            .collect();
+        out.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        out
+    }
+}
+
 // Strategy:
 // - Use an mmap to access the file. This has the advantage of avoiding String
 //   allocations and directly references bytestrings.
@@ -161,7 +295,9 @@ pub fn read_mmap(mmap: &Mmap) -> Vec<(&BStr, CityDetails)> {
 //
 // Remarks: This seems to make performance very slightly better.
 pub fn read_mmap_unsafe(mmap: &Mmap) -> Vec<(&BStr, CityDetails)> {
-    let mut map: HashMap<&BStr, CityDetails> = HashMap::default();
+    // Unlike `read_mmap`, entries go into a `StationTable`, and with the `swar`
+    // feature measurements are parsed by `parse_temp_swar`.
+    let mut table = StationTable::default();
 
     let mut city: &BStr;
     let mut numeric: &BStr;
@@ -176,8 +312,96 @@ pub fn read_mmap_unsafe(mmap: &Mmap) -> Vec<(&BStr, CityDetails)> {
             numeric = mmap.get_unchecked(numeric_start..numeric_start + i).into();
             city_start = numeric_start + i + 1;
 
+            #[cfg(feature = "swar")]
+            let meas = parse_temp_swar(numeric);
+            #[cfg(not(feature = "swar"))]
             let meas = parse_digits(numeric);
-            map.entry(city).or_default().update(meas);
+            let hash = fxhash(city);
+            table.entry_or_default(hash, city).update(meas);
+        }
+    }
+
+    table.into_sorted_vec()
+}
+
+// Strategy:
+// - The same line-splitting loop as `read_mmap_unsafe`, but the mapped bytes are split
+//   into `threads` roughly-equal ranges and each worker owns a slice. The split
+//   points are nudged forward to the next '\n' so no line is ever cut in half.
+// - Each worker builds its own HashMap, and the partial results are merged into
+//   one map once the threads have joined.
+// - `std::thread::scope` lets the workers borrow `&Mmap` slices directly without
+//   needing `'static` bounds or an `Arc`.
+//
+// Remarks: parallelism is where the external walkthroughs get their biggest
+// wins; on a many-core machine this scales close to linearly with `threads`.
+pub fn read_mmap_parallel(mmap: &Mmap, threads: usize) -> Vec<(&BStr, CityDetails)> {
+    let len = mmap.len();
+    let threads = threads.max(1);
+
+    // Compute the chunk boundaries. Each chunk starts one past the previous
+    // chunk's adjusted newline, so the first chunk starts at 0 and the last
+    // chunk ends at `len`.
+    let mut bounds = Vec::with_capacity(threads + 1);
+    bounds.push(0);
+    for t in 1..threads {
+        let guess = len * t / threads;
+        // Nudge the split forward to just past the next newline.
+        let end = match memchr::memchr(b'\n', &mmap[guess..]) {
+            Some(i) => guess + i + 1,
+            None => len,
+        };
+        // Avoid empty or out-of-order chunks on small inputs.
+        if end > *bounds.last().unwrap() {
+            bounds.push(end);
+        }
+    }
+    bounds.push(len);
+    bounds.dedup();
+
+    let mut partials: Vec<HashMap<&BStr, CityDetails>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = bounds
+            .windows(2)
+            .map(|w| {
+                let (start, end) = (w[0], w[1]);
+                let slice = &mmap[start..end];
+                scope.spawn(move || {
+                    let mut map: HashMap<&BStr, CityDetails> = HashMap::default();
+
+                    let mut city: &BStr;
+                    let mut numeric: &BStr;
+                    let mut city_start = 0;
+                    let mut numeric_start;
+                    unsafe {
+                        while city_start < slice.len() {
+                            let i = memchr::memchr(b';', slice.get_unchecked(city_start..))
+                                .unwrap_unchecked();
+                            city = slice.get_unchecked(city_start..city_start + i).into();
+                            numeric_start = city_start + i + 1;
+                            let i = memchr::memchr(b'\n', slice.get_unchecked(numeric_start..))
+                                .unwrap_unchecked();
+                            numeric = slice
+                                .get_unchecked(numeric_start..numeric_start + i)
+                                .into();
+                            city_start = numeric_start + i + 1;
+
+                            let meas = parse_digits(numeric);
+                            map.entry(city).or_default().update(meas);
+                        }
+                    }
+                    map
+                })
+            })
+            .collect();
+
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    // Merge the per-thread maps into the last one.
+    let mut map = partials.pop().unwrap_or_default();
+    for partial in partials {
+        for (city, details) in partial {
+            map.entry(city).or_default().merge(&details);
         }
     }
 
@@ -186,6 +410,101 @@ pub fn read_mmap_unsafe(mmap: &Mmap) -> Vec<(&BStr, CityDetails)> {
     map
 }
 
+// Strategy:
+// - mmap needs a real seekable file, so for pipes/stdin we read into a reusable
+//   fixed-size buffer instead. memchr scans each refill for complete lines, and
+//   the trailing partial line is copied to the front of the buffer before the
+//   next `read` so it joins the bytes that complete it.
+// - Parse each line with the same `memchr(b';')` + `parse_digits` combo as the
+//   mmap readers, but keys are owned `Vec<u8>` since the buffer contents are
+//   transient and get overwritten on the next refill.
+//
+// Remarks: this makes the crate usable in shell pipelines and on files too large
+// to map, without paying the `read_line`/`String` cost of `read_naive`.
+pub fn read_streaming<R: BufRead>(mut reader: R) -> Vec<(Vec<u8>, CityDetails)> {
+    // A couple of MiB amortises the syscall cost while staying cache-friendly.
+    const BUF_SIZE: usize = 2 * 1024 * 1024;
+
+    let mut map: HashMap<Vec<u8>, CityDetails> = HashMap::default();
+    let mut buf = vec![0u8; BUF_SIZE];
+    // Number of valid bytes at the front of `buf`: a carried-over partial line
+    // plus whatever the latest `read` appended.
+    let mut filled = 0;
+
+    loop {
+        let n = reader.read(&mut buf[filled..]).unwrap();
+        if n == 0 {
+            break;
+        }
+        let available = filled + n;
+
+        let mut start = 0;
+        while let Some(nl) = memchr::memchr(b'\n', &buf[start..available]) {
+            let line = &buf[start..start + nl];
+            let sep = memchr::memchr(b';', line).unwrap();
+            let meas = parse_digits(&line[sep + 1..]);
+            map.entry(line[..sep].to_vec()).or_default().update(meas);
+            start += nl + 1;
+        }
+
+        // Shuffle the unconsumed tail back to the front for the next refill.
+        buf.copy_within(start..available, 0);
+        filled = available - start;
+    }
+
+    // A trailing line without a final newline still needs to be counted.
+    if filled > 0 {
+        if let Some(sep) = memchr::memchr(b';', &buf[..filled]) {
+            let meas = parse_digits(&buf[sep + 1..filled]);
+            map.entry(buf[..sep].to_vec()).or_default().update(meas);
+        }
+    }
+
+    let mut map = map.into_iter().collect::<Vec<_>>();
+    map.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    map
+}
+
+// Strategy:
+// - Hide the `File`/`MmapOptions`/unsafe-map juggling behind one safe entry
+//   point. The `Mmap` is created and dropped entirely inside this function, so
+//   it never escapes to callers; the borrowed bytestring keys are copied into
+//   owned `String`s on the way out.
+// - The backend is chosen at compile time: the default maps the file and runs
+//   the single-threaded `read_mmap_unsafe`, the `rayon` feature routes to the
+//   parallel reader, and the `naive` feature skips mmap entirely (for platforms
+//   where mapping is undesirable).
+//
+// Remarks: library consumers get one obvious function instead of having to
+// reach for `memmap2` themselves.
+pub fn process_path(path: &Path) -> Vec<(String, CityDetails)> {
+    let file = File::open(path).unwrap();
+
+    #[cfg(feature = "naive")]
+    {
+        return read_naive(file);
+    }
+
+    #[cfg(not(feature = "naive"))]
+    {
+        let mmap = unsafe { MmapOptions::new().map(&file).unwrap() };
+
+        #[cfg(feature = "rayon")]
+        let map = {
+            let threads = std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1);
+            read_mmap_parallel(&mmap, threads)
+        };
+        #[cfg(not(feature = "rayon"))]
+        let map = read_mmap_unsafe(&mmap);
+
+        map.into_iter()
+            .map(|(city, details)| (city.to_string(), details))
+            .collect()
+    }
+}
+
 pub fn print(map: impl IntoIterator<Item = (impl Display, CityDetails)>) {
     let mut map = map.into_iter();
 
@@ -198,3 +517,45 @@ pub fn print(map: impl IntoIterator<Item = (impl Display, CityDetails)>) {
     }
     println!("}}");
 }
+
+/// Selects how [`print_with`] renders results.
+pub enum OutputFormat {
+    /// The canonical `{city=min/mean/max, ...}` line.
+    Canonical,
+
+    /// One `city\tmin\tmean\tmax` row per station, for downstream tooling.
+    Tsv,
+}
+
+/// Like [`print`], but lets the caller pick a machine-readable TSV layout in
+/// addition to the canonical line. Both formats share the same half-up rounded
+/// statistics.
+pub fn print_with(map: impl IntoIterator<Item = (impl Display, CityDetails)>, format: OutputFormat) {
+    match format {
+        OutputFormat::Canonical => print(map),
+        OutputFormat::Tsv => {
+            for (city, details) in map {
+                println!(
+                    "{city}\t{:.1}\t{:.1}\t{:.1}",
+                    details.min as f64 / 10.0,
+                    details.rounded_mean(),
+                    details.max as f64 / 10.0
+                );
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "swar"))]
+mod tests {
+    use super::parse_temp_swar;
+
+    #[test]
+    fn swar_matches_known_values() {
+        assert_eq!(parse_temp_swar(b"0.0"), 0);
+        assert_eq!(parse_temp_swar(b"-0.1"), -1);
+        assert_eq!(parse_temp_swar(b"9.9"), 99);
+        assert_eq!(parse_temp_swar(b"-99.9"), -999);
+        assert_eq!(parse_temp_swar(b"99.9"), 999);
+    }
+}